@@ -0,0 +1,790 @@
+use std::{fmt, str};
+
+use serde::de::{
+    self, DeserializeSeed, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor,
+};
+use serde::Deserialize;
+
+use crate::Error;
+
+/// Deserializer for RESP2 and RESP3 wire data.
+///
+/// Reads the wire types [`crate::Serializer`] emits: RESP2's integers
+/// (`:`), strings (`+`/`$`), arrays (`*`), the empty array used for `None`
+/// and the null bulk string used for unit (the null array `*-1` also reads
+/// as `None`), and error replies (`-`, which surface as a
+/// deserialization error rather than a string); plus RESP3's booleans
+/// (`#t`/`#f`), doubles (`,`, including `inf`/`-inf`/`nan`), the null type
+/// (`_`), native maps (`%`) and sets (`~`)/pushes (`>`), big numbers (`(`),
+/// verbatim strings (`=`), and bulk errors (`!`, which surface as an error
+/// the same way `-` does). Which wire type is present is read off each
+/// value's leading tag byte, so one `Deserializer` reads either protocol
+/// version without being told in advance which one to expect.
+///
+/// Not every value round-trips. Integers (`:`) are parsed as `i64`, so a
+/// `u64` above `i64::MAX` is rejected. Floats and out-of-range 128-bit
+/// integers, which RESP2 writes as strings, are not read back as numbers.
+/// In RESP3 output, an `Option` holding an empty or one-element sequence
+/// is read back as `None` or rejected, since the `*0`/`*1` headers are
+/// taken as RESP2's `None`/`Some` encodings. `StructConfig::Flat`'s
+/// values-only arrays are not read as structs.
+pub struct Deserializer<'de> {
+    input: &'de [u8],
+}
+
+impl<'de> Deserializer<'de> {
+    /// Create a deserializer that reads RESP2 or RESP3 from `input`; the
+    /// wire format is detected per-value from its leading tag byte.
+    pub fn new(input: &'de str) -> Self {
+        Deserializer { input: input.as_bytes() }
+    }
+}
+
+/// Deserialize an instance of `T` from a RESP2- or RESP3-encoded string.
+pub fn from_str<'a, T>(s: &'a str) -> crate::Result<T>
+where
+    T: Deserialize<'a>,
+{
+    let mut deserializer = Deserializer::new(s);
+    T::deserialize(&mut deserializer)
+}
+
+fn custom<T: fmt::Display>(msg: T) -> Error {
+    <Error as de::Error>::custom(msg)
+}
+
+impl<'de> Deserializer<'de> {
+    fn peek_tag(&self) -> crate::Result<u8> {
+        self.input.first().copied().ok_or_else(|| custom("unexpected end of input"))
+    }
+
+    fn next_line(&mut self) -> crate::Result<&'de [u8]> {
+        let pos = self
+            .input
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or_else(|| custom("unterminated RESP line"))?;
+        let line = &self.input[..pos];
+        self.input = &self.input[pos + 2..];
+        Ok(line)
+    }
+
+    fn parse_simple_string(&mut self) -> crate::Result<&'de str> {
+        self.peek_tag()?;
+        self.input = &self.input[1..];
+        let line = self.next_line()?;
+        str::from_utf8(line).map_err(|_| custom("invalid UTF-8 in simple string"))
+    }
+
+    /// A `-<msg>\r\n` error reply. RESP treats this as a protocol-level
+    /// error rather than ordinary data, so it is surfaced as an `Err`
+    /// instead of a string value.
+    fn parse_error_reply(&mut self) -> crate::Result<Error> {
+        self.peek_tag()?;
+        self.input = &self.input[1..];
+        let line = self.next_line()?;
+        Ok(custom(String::from_utf8_lossy(line)))
+    }
+
+    fn parse_integer(&mut self) -> crate::Result<i64> {
+        self.peek_tag()?;
+        self.input = &self.input[1..];
+        let line = self.next_line()?;
+        str::from_utf8(line)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| custom("invalid integer"))
+    }
+
+    /// A `$<len>\r\n<data>\r\n` bulk string, or `None` for the RESP2 null
+    /// bulk string `$-1\r\n`.
+    fn parse_bulk_string(&mut self) -> crate::Result<Option<&'de [u8]>> {
+        self.peek_tag()?;
+        self.input = &self.input[1..];
+        let line = self.next_line()?;
+        let len: i64 = str::from_utf8(line)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| custom("invalid bulk string length"))?;
+        if len < 0 {
+            return Ok(None);
+        }
+        let len = len as usize;
+        if self.input.len() < len + 2 {
+            return Err(custom("truncated bulk string"));
+        }
+        let data = &self.input[..len];
+        self.input = &self.input[len + 2..];
+        Ok(Some(data))
+    }
+
+    /// A length header shared by the RESP2 array (`*<len>\r\n`, or `None`
+    /// for the null array `*-1\r\n`) and the RESP3 set (`~<len>\r\n`) and
+    /// push (`><len>\r\n`) headers, which all share this `<tag><len>\r\n`
+    /// shape and differ only in their leading tag byte.
+    fn parse_array_len(&mut self) -> crate::Result<Option<usize>> {
+        self.peek_tag()?;
+        self.input = &self.input[1..];
+        let line = self.next_line()?;
+        let len: i64 = str::from_utf8(line)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| custom("invalid array length"))?;
+        if len < 0 {
+            Ok(None)
+        } else {
+            Ok(Some(len as usize))
+        }
+    }
+
+    /// A `%<len>\r\n` RESP3 map header. `len` counts key/value pairs, not
+    /// the flat item count.
+    fn parse_map_len(&mut self) -> crate::Result<usize> {
+        self.peek_tag()?;
+        self.input = &self.input[1..];
+        let line = self.next_line()?;
+        str::from_utf8(line)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| custom("invalid map length"))
+    }
+
+    /// A `#t\r\n`/`#f\r\n` RESP3 boolean.
+    fn parse_bool(&mut self) -> crate::Result<bool> {
+        self.peek_tag()?;
+        self.input = &self.input[1..];
+        match self.next_line()? {
+            b"t" => Ok(true),
+            b"f" => Ok(false),
+            _ => Err(custom("invalid boolean")),
+        }
+    }
+
+    /// A `,<value>\r\n` RESP3 double (`,inf`/`,-inf`/`,nan` for the
+    /// non-finite cases, which `f64`'s `FromStr` parses directly).
+    fn parse_double(&mut self) -> crate::Result<f64> {
+        self.peek_tag()?;
+        self.input = &self.input[1..];
+        let line = self.next_line()?;
+        str::from_utf8(line)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| custom("invalid double"))
+    }
+
+    /// A `_\r\n` RESP3 null.
+    fn parse_null(&mut self) -> crate::Result<()> {
+        self.peek_tag()?;
+        self.input = &self.input[1..];
+        if self.next_line()?.is_empty() {
+            Ok(())
+        } else {
+            Err(custom("invalid null"))
+        }
+    }
+
+    /// A `(<digits>\r\n` RESP3 big number, used for 128-bit integers
+    /// outside i64/u64 range. Returns the raw digit text (with a leading
+    /// `-` for negative values) for the caller to parse as i128 or u128.
+    fn parse_big_number(&mut self) -> crate::Result<&'de str> {
+        self.peek_tag()?;
+        self.input = &self.input[1..];
+        let line = self.next_line()?;
+        str::from_utf8(line).map_err(|_| custom("invalid UTF-8 in big number"))
+    }
+
+    /// A `=<len>\r\ntxt:<payload>\r\n` RESP3 verbatim string. Returns just
+    /// the text after the 3-byte format code and `:` separator, the same
+    /// way [`Self::parse_bulk_string`] returns just a bulk string's
+    /// payload.
+    fn parse_verbatim(&mut self) -> crate::Result<&'de str> {
+        self.peek_tag()?;
+        self.input = &self.input[1..];
+        let line = self.next_line()?;
+        let len: i64 = str::from_utf8(line)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| custom("invalid verbatim string length"))?;
+        if len < 0 {
+            return Err(custom("invalid verbatim string length"));
+        }
+        let len = len as usize;
+        if self.input.len() < len + 2 {
+            return Err(custom("truncated verbatim string"));
+        }
+        let data = &self.input[..len];
+        self.input = &self.input[len + 2..];
+        let text = data.get(4..).ok_or_else(|| custom("truncated verbatim string format prefix"))?;
+        str::from_utf8(text).map_err(|_| custom("invalid UTF-8 in verbatim string"))
+    }
+
+    /// A `!<len>\r\n<msg>\r\n` RESP3 bulk error. Like `-`, RESP treats this
+    /// as a protocol-level error rather than ordinary data.
+    fn parse_bulk_error(&mut self) -> crate::Result<Error> {
+        self.peek_tag()?;
+        self.input = &self.input[1..];
+        let line = self.next_line()?;
+        let len: i64 = str::from_utf8(line)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| custom("invalid bulk error length"))?;
+        if len < 0 {
+            return Err(custom("invalid bulk error length"));
+        }
+        let len = len as usize;
+        if self.input.len() < len + 2 {
+            return Err(custom("truncated bulk error"));
+        }
+        let data = &self.input[..len];
+        self.input = &self.input[len + 2..];
+        Ok(custom(String::from_utf8_lossy(data)))
+    }
+
+    fn parse_string(&mut self) -> crate::Result<String> {
+        match self.peek_tag()? {
+            b'+' => Ok(self.parse_simple_string()?.to_string()),
+            b'-' => Err(self.parse_error_reply()?),
+            b'$' => match self.parse_bulk_string()? {
+                Some(bytes) => {
+                    String::from_utf8(bytes.to_vec()).map_err(|_| custom("invalid UTF-8 in bulk string"))
+                }
+                None => Err(custom("expected a string, found the null bulk string")),
+            },
+            b'=' => Ok(self.parse_verbatim()?.to_string()),
+            b'!' => Err(self.parse_bulk_error()?),
+            tag => Err(custom(format!("expected a string, found RESP type `{}`", tag as char))),
+        }
+    }
+}
+
+/// Walks the elements of a `*<len>\r\n` array.
+struct Seq<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    remaining: usize,
+}
+
+impl<'de, 'a> SeqAccess<'de> for Seq<'a, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> crate::Result<Option<T::Value>>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+impl<'de, 'a> MapAccess<'de> for Seq<'a, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> crate::Result<Option<K::Value>>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> crate::Result<V::Value>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        if self.remaining == 0 {
+            return Err(custom("no value for the last map key"));
+        }
+        self.remaining -= 1;
+        seed.deserialize(&mut *self.de)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining / 2)
+    }
+}
+
+impl<'de> EnumAccess<'de> for &mut Deserializer<'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> crate::Result<(V::Value, Self::Variant)>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(&mut *self)?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for &mut Deserializer<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> crate::Result<()> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> crate::Result<T::Value>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> crate::Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        de::Deserializer::deserialize_tuple(self, len, visitor)
+    }
+
+    fn struct_variant<V>(self, fields: &'static [&'static str], visitor: V) -> crate::Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        de::Deserializer::deserialize_struct(self, "", fields, visitor)
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> crate::Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.peek_tag()? {
+            b'+' => visitor.visit_borrowed_str(self.parse_simple_string()?),
+            b'-' => Err(self.parse_error_reply()?),
+            b':' => visitor.visit_i64(self.parse_integer()?),
+            b'$' => match self.parse_bulk_string()? {
+                Some(bytes) => match str::from_utf8(bytes) {
+                    Ok(s) => visitor.visit_borrowed_str(s),
+                    Err(_) => visitor.visit_borrowed_bytes(bytes),
+                },
+                None => visitor.visit_none(),
+            },
+            b'*' => match self.parse_array_len()? {
+                Some(len) => visitor.visit_seq(Seq { de: self, remaining: len }),
+                None => visitor.visit_none(),
+            },
+            b'#' => visitor.visit_bool(self.parse_bool()?),
+            b',' => visitor.visit_f64(self.parse_double()?),
+            b'_' => {
+                self.parse_null()?;
+                visitor.visit_none()
+            }
+            b'%' => {
+                let len = self.parse_map_len()?;
+                visitor.visit_map(Seq { de: self, remaining: len * 2 })
+            }
+            b'~' | b'>' => match self.parse_array_len()? {
+                Some(len) => visitor.visit_seq(Seq { de: self, remaining: len }),
+                None => Err(custom("expected a set/push, found a negative length")),
+            },
+            b'(' => {
+                let digits = self.parse_big_number()?;
+                match digits.parse::<i128>() {
+                    Ok(v) => visitor.visit_i128(v),
+                    Err(_) => {
+                        let v: u128 = digits.parse().map_err(|_| custom("invalid big number"))?;
+                        visitor.visit_u128(v)
+                    }
+                }
+            }
+            b'=' => visitor.visit_borrowed_str(self.parse_verbatim()?),
+            b'!' => Err(self.parse_bulk_error()?),
+            tag => Err(custom(format!("unrecognized RESP type `{}`", tag as char))),
+        }
+    }
+
+    // RESP3: `#t\r\n`/`#f\r\n`. RESP2 has no boolean type, so the
+    // serializer falls back to the integer `0`/`1`.
+    fn deserialize_bool<V>(self, visitor: V) -> crate::Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.peek_tag()? {
+            b'#' => visitor.visit_bool(self.parse_bool()?),
+            _ => visitor.visit_bool(self.parse_integer()? != 0),
+        }
+    }
+
+    fn deserialize_i64<V>(self, visitor: V) -> crate::Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_i64(self.parse_integer()?)
+    }
+
+    fn deserialize_u64<V>(self, visitor: V) -> crate::Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let v = self.parse_integer()?;
+        visitor.visit_u64(u64::try_from(v).map_err(|_| custom("negative integer for an unsigned type"))?)
+    }
+
+    fn deserialize_str<V>(self, visitor: V) -> crate::Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_string(self.parse_string()?)
+    }
+
+    fn deserialize_bytes<V>(self, visitor: V) -> crate::Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.parse_bulk_string()? {
+            Some(bytes) => visitor.visit_borrowed_bytes(bytes),
+            None => Err(custom("expected bytes, found the null bulk string")),
+        }
+    }
+
+    // RESP3 represents `None` as the null type `_\r\n` directly. RESP2 has
+    // no dedicated null, so it falls back to an empty array for `None` and
+    // a one-element array wrapping `v` for `Some(v)` -- see
+    // `Serializer::serialize_none`/`serialize_some`. The null bulk string
+    // and the null array are read as `None` too.
+    fn deserialize_option<V>(self, visitor: V) -> crate::Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.peek_tag()? {
+            b'_' => {
+                self.parse_null()?;
+                visitor.visit_none()
+            }
+            b'$' => {
+                let save = self.input;
+                if self.parse_bulk_string()?.is_none() {
+                    visitor.visit_none()
+                } else {
+                    self.input = save;
+                    visitor.visit_some(self)
+                }
+            }
+            b'*' => {
+                let save = self.input;
+                match self.parse_array_len()? {
+                    None | Some(0) => visitor.visit_none(),
+                    Some(1) => visitor.visit_some(self),
+                    Some(_) => {
+                        self.input = save;
+                        visitor.visit_some(self)
+                    }
+                }
+            }
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    // RESP3: the null type `_\r\n`. RESP2 has no dedicated null type, so
+    // the serializer spells `()` as the null bulk string `$-1\r\n`.
+    fn deserialize_unit<V>(self, visitor: V) -> crate::Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.peek_tag()? {
+            b'_' => {
+                self.parse_null()?;
+                visitor.visit_unit()
+            }
+            _ => match self.parse_bulk_string()? {
+                None => visitor.visit_unit(),
+                Some(_) => Err(custom("expected the null bulk string for a unit value")),
+            },
+        }
+    }
+
+    fn deserialize_unit_struct<V>(self, _name: &'static str, visitor: V) -> crate::Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> crate::Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    // `parse_array_len` doesn't look at the tag byte's value, so this
+    // accepts RESP2 arrays (`*`) and RESP3 sets (`~`)/pushes (`>`) alike --
+    // they all share the same `<tag><len>\r\n` header shape.
+    fn deserialize_seq<V>(self, visitor: V) -> crate::Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.parse_array_len()? {
+            Some(len) => visitor.visit_seq(Seq { de: self, remaining: len }),
+            None => Err(custom("expected an array, found the null array")),
+        }
+    }
+
+    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> crate::Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> crate::Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    // Under RESP3, a map is the native `%<len>\r\n` type, a flat sequence
+    // of `len * 2` key/value items -- the same shape `Seq`'s `MapAccess`
+    // impl already walks for other callers. Under RESP2 (or `StructConfig`
+    // modes that degrade to it), a `#[derive(Serialize)]` struct or map is
+    // emitted as the pair-array `[[k0,v0], [k1,v1], ...]` instead, handled
+    // by `PairArray`. `StructConfig::Flat`'s values-only array isn't
+    // recoverable here without the field names at the call site, so it
+    // isn't supported by this reader.
+    fn deserialize_map<V>(self, visitor: V) -> crate::Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.peek_tag()? {
+            b'%' => {
+                let len = self.parse_map_len()?;
+                visitor.visit_map(Seq { de: self, remaining: len * 2 })
+            }
+            _ => match self.parse_array_len()? {
+                Some(len) => visitor.visit_map(PairArray { de: self, remaining: len }),
+                None => Err(custom("expected a map, found the null array")),
+            },
+        }
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> crate::Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_map(visitor)
+    }
+
+    // A unit variant is its bare name; a variant carrying data is the
+    // two-element array `[variant, value]`.
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> crate::Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if self.peek_tag()? == b'*' {
+            match self.parse_array_len()? {
+                Some(2) => {}
+                _ => return Err(custom("expected a `[variant, value]` pair")),
+            }
+        }
+        visitor.visit_enum(self)
+    }
+
+    fn deserialize_identifier<V>(self, visitor: V) -> crate::Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_ignored_any<V>(self, visitor: V) -> crate::Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_any(visitor)
+    }
+
+    serde::forward_to_deserialize_any! {
+        i8 i16 i32 i128 u8 u16 u32 u128 f32 f64 char string byte_buf
+    }
+}
+
+/// Walks the `[[k0,v0], [k1,v1], ...]` pair-array shape `StructConfig`'s
+/// `Auto`/`PairArray` modes emit for structs and maps under RESP2.
+struct PairArray<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    remaining: usize,
+}
+
+impl<'de, 'a> MapAccess<'de> for PairArray<'a, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> crate::Result<Option<K::Value>>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        match self.de.parse_array_len()? {
+            Some(2) => {}
+            _ => return Err(custom("expected a `[key, value]` pair")),
+        }
+        self.remaining -= 1;
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> crate::Result<V::Value>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        seed.deserialize(&mut *self.de)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+
+    #[test]
+    fn test_integer() {
+        assert_eq!(from_str::<i64>(":42\r\n").unwrap(), 42);
+        assert_eq!(from_str::<u64>(":42\r\n").unwrap(), 42);
+    }
+
+    #[test]
+    fn test_bool() {
+        assert!(from_str::<bool>(":1\r\n").unwrap());
+        assert!(!from_str::<bool>(":0\r\n").unwrap());
+    }
+
+    #[test]
+    fn test_resp3_scalars() {
+        assert!(from_str::<bool>("#t\r\n").unwrap());
+        assert!(!from_str::<bool>("#f\r\n").unwrap());
+        assert_eq!(from_str::<f64>(",1.5\r\n").unwrap(), 1.5);
+        assert_eq!(from_str::<f64>(",inf\r\n").unwrap(), f64::INFINITY);
+        assert_eq!(from_str::<f64>(",-inf\r\n").unwrap(), f64::NEG_INFINITY);
+        assert!(from_str::<f64>(",nan\r\n").unwrap().is_nan());
+        assert_eq!(from_str::<Option<u32>>("_\r\n").unwrap(), None);
+        assert_eq!(from_str::<()>("_\r\n").unwrap(), ());
+        assert_eq!(from_str::<i128>("(170141183460469231731687303715884105727\r\n").unwrap(),
+            170141183460469231731687303715884105727i128);
+        assert_eq!(from_str::<u128>("(340282366920938463463374607431768211455\r\n").unwrap(),
+            340282366920938463463374607431768211455u128);
+        assert_eq!(from_str::<String>("=15\r\ntxt:Some string\r\n").unwrap(), "Some string");
+    }
+
+    #[test]
+    fn test_resp3_bulk_error() {
+        let err = from_str::<String>("!19\r\nERR unknown command\r\n").unwrap_err();
+        assert_eq!(err.to_string(), "ERR unknown command");
+    }
+
+    #[test]
+    fn test_resp3_map() {
+        use std::collections::BTreeMap;
+
+        let mut map = BTreeMap::new();
+        map.insert("key".to_string(), "foo".to_string());
+        assert_eq!(from_str::<BTreeMap<String, String>>("%1\r\n+key\r\n+foo\r\n").unwrap(), map);
+    }
+
+    #[test]
+    fn test_resp3_set_and_push() {
+        assert_eq!(from_str::<Vec<i64>>("~3\r\n:1\r\n:2\r\n:3\r\n").unwrap(), vec![1, 2, 3]);
+        assert_eq!(from_str::<Vec<i64>>(">3\r\n:1\r\n:2\r\n:3\r\n").unwrap(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_string() {
+        assert_eq!(from_str::<String>("+OK\r\n").unwrap(), "OK");
+        assert_eq!(from_str::<String>("$5\r\nhello\r\n").unwrap(), "hello");
+    }
+
+    #[test]
+    fn test_error_reply() {
+        let err = from_str::<String>("-ERR unknown command\r\n").unwrap_err();
+        assert_eq!(err.to_string(), "ERR unknown command");
+    }
+
+    #[test]
+    fn test_seq() {
+        assert_eq!(from_str::<Vec<i64>>("*2\r\n:1\r\n:2\r\n").unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_option() {
+        assert_eq!(from_str::<Option<i64>>("$-1\r\n").unwrap(), None);
+        assert_eq!(from_str::<Option<i64>>("*1\r\n:1\r\n").unwrap(), Some(1));
+    }
+
+    #[test]
+    fn test_struct_round_trip() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Test {
+            int: u32,
+            seq: Vec<String>,
+        }
+
+        let test = Test { int: 1, seq: vec!["a".to_string(), "b".to_string()] };
+        let encoded = crate::to_string(&test).unwrap();
+        assert_eq!(from_str::<Test>(&encoded).unwrap(), test);
+    }
+
+    #[test]
+    fn test_resp3_struct_round_trip() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Test {
+            flag: bool,
+            value: Option<f64>,
+            seq: Vec<String>,
+        }
+
+        let test = Test { flag: true, value: Some(1.5), seq: vec!["a".to_string(), "b".to_string()] };
+        let encoded = crate::to_string_resp3(&test).unwrap();
+        assert_eq!(from_str::<Test>(&encoded).unwrap(), test);
+    }
+
+    // Malformed/truncated input (e.g. a short socket read) must surface as
+    // an `Err`, never a slice-index panic.
+    #[test]
+    fn test_empty_input_does_not_panic() {
+        assert!(from_str::<i64>("").is_err());
+        assert!(from_str::<u64>("").is_err());
+        assert!(from_str::<bool>("").is_err());
+        assert!(from_str::<()>("").is_err());
+        assert!(from_str::<String>("").is_err());
+        assert!(from_str::<Vec<i64>>("").is_err());
+    }
+
+    #[test]
+    fn test_truncated_input_does_not_panic() {
+        assert!(from_str::<i64>(":4").is_err());
+        assert!(from_str::<String>("$5\r\nhel").is_err());
+        assert!(from_str::<Vec<i64>>("*2\r\n:1\r\n").is_err());
+    }
+}
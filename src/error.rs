@@ -5,59 +5,137 @@ use serde::{de, ser};
 /// Alias type for Result<T, Error>
 pub type Result<T> = result::Result<T, Error>;
 
-/// Meaning of a error
+/// A single breadcrumb recording where, inside a compound value, an error
+/// occurred: a struct/map field name or a sequence/tuple index.
+#[derive(Debug)]
+enum PathSegment {
+    /// A named struct/map field.
+    Field(&'static str),
+    /// A sequence or tuple index.
+    Index(usize),
+}
+
+impl fmt::Display for PathSegment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathSegment::Field(name) => write!(f, "field `{}`", name),
+            PathSegment::Index(i) => write!(f, "index {}", i),
+        }
+    }
+}
+
+/// What actually went wrong, without the breadcrumb trail. See [`Error`].
 #[derive(Debug)]
-pub enum Error {
-    /// Error that only contains error message, usually came from ser/de error
+enum ErrorKind {
+    /// Error that only contains an error message, usually raised by serde
+    /// itself via `ser::Error::custom`/`de::Error::custom`.
     Msg(String),
     /// Due to restriction of RESP, array len must known to be used as prefix
     LenNotKnown,
-    /// Write error
-    Io,
-    /// Write buf cannot be represented by valid UTF-8 string
-    Utf8,
+    /// A `SimpleError`/`BulkError` message contained `\r` or `\n`, which
+    /// would let it break out of its own `-<msg>\r\n` line and forge extra
+    /// RESP frames.
+    CrlfInErrorMessage,
+    /// Write error. Preserves the original `io::Error` as `source()`.
+    Io(io::Error),
+    /// Write buf cannot be represented by valid UTF-8 string. Preserves the
+    /// original `FromUtf8Error` as `source()`.
+    Utf8(string::FromUtf8Error),
 }
 
-impl de::Error for Error {
-    fn custom<T: fmt::Display>(msg: T) -> Self {
-        Error::Msg(msg.to_string())
+/// Meaning of a error
+///
+/// Carries a breadcrumb trail of the fields/indices traversed before the
+/// failure, displayed outermost first, so a failure nested inside
+/// `struct { seq: Vec<T> }`'s second element displays as
+/// `field \`seq\` -> index 1 -> <cause>`.
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    path: Vec<PathSegment>,
+}
+
+impl Error {
+    fn new(kind: ErrorKind) -> Self {
+        Error { kind, path: Vec::new() }
+    }
+
+    /// The error raised when a `Serialize` impl reports an unknown sequence
+    /// or map length, which RESP cannot encode since its `*`/`%` prefixes
+    /// need the length upfront.
+    pub(crate) fn len_not_known() -> Self {
+        Error::new(ErrorKind::LenNotKnown)
+    }
+
+    /// The error raised when a `SimpleError`/`BulkError` message contains
+    /// `\r`/`\n`, which the RESP2 simple-error line can't represent without
+    /// letting the message forge extra frames on the wire.
+    pub(crate) fn crlf_in_error_message() -> Self {
+        Error::new(ErrorKind::CrlfInErrorMessage)
+    }
+
+    /// Record that this error occurred while serializing the struct/map
+    /// field `name`. Called by `SerializeStruct`/`SerializeStructVariant`
+    /// as the error unwinds back out through nested fields.
+    pub fn field(mut self, name: &'static str) -> Self {
+        self.path.push(PathSegment::Field(name));
+        self
+    }
+
+    /// Record that this error occurred while serializing the element at
+    /// `index`. Called by `SerializeSeq`/`SerializeTuple`/`SerializeMap` as
+    /// the error unwinds back out through nested elements.
+    pub fn index(mut self, index: usize) -> Self {
+        self.path.push(PathSegment::Index(index));
+        self
     }
 }
 
-impl ser::Error for Error {
+impl de::Error for Error {
     fn custom<T: fmt::Display>(msg: T) -> Self {
-        Error::Msg(msg.to_string())
+        Error::new(ErrorKind::Msg(msg.to_string()))
     }
 }
 
-impl Error {
-    fn as_str(&self) -> &str {
-        match self {
-            _ => "other error",
-        }
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::new(ErrorKind::Msg(msg.to_string()))
     }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.as_str())
+        for segment in self.path.iter().rev() {
+            write!(f, "{} -> ", segment)?;
+        }
+        match &self.kind {
+            ErrorKind::Msg(msg) => write!(f, "{}", msg),
+            ErrorKind::LenNotKnown => write!(f, "sequence or map length must be known up front to serialize as RESP"),
+            ErrorKind::CrlfInErrorMessage => write!(f, "error message must not contain '\\r' or '\\n'"),
+            ErrorKind::Io(e) => write!(f, "{}", e),
+            ErrorKind::Utf8(e) => write!(f, "{}", e),
+        }
     }
 }
 
 impl error::Error for Error {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
-        None
+        match &self.kind {
+            ErrorKind::Io(e) => Some(e),
+            ErrorKind::Utf8(e) => Some(e),
+            ErrorKind::Msg(_) | ErrorKind::LenNotKnown | ErrorKind::CrlfInErrorMessage => None,
+        }
     }
 }
 
 impl From<io::Error> for Error {
-    fn from(_: io::Error) -> Self {
-        Error::Io
+    fn from(e: io::Error) -> Self {
+        Error::new(ErrorKind::Io(e))
     }
 }
 
 impl From<string::FromUtf8Error> for Error {
-    fn from(_: string::FromUtf8Error) -> Self {
-        Error::Utf8
+    fn from(e: string::FromUtf8Error) -> Self {
+        Error::new(ErrorKind::Utf8(e))
     }
 }
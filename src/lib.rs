@@ -2,10 +2,21 @@
 
 //! A crate for serialize and deserialize data
 //! into RESP(REdis Serializable Protocol) representation
+//!
+//! Supports both RESP2 and RESP3. [`Serializer`]/[`to_string`]/
+//! [`to_writer`] emit RESP2 by default, or RESP3 (native maps, sets,
+//! doubles, booleans, nulls, big numbers, verbatim strings, and the
+//! first-class/bulk error wrapper types) via [`to_string_resp3`]/
+//! [`to_writer_resp3`]. [`Deserializer`]/[`from_str`] read either wire
+//! format back, picking the type off each value's leading tag byte;
+//! [`Deserializer`] lists the values that do not round-trip.
 
 pub use de::{Deserializer, from_str};
 pub use error::{Error, Result};
-pub use ser::{Serializer, to_string};
+pub use ser::{
+    BulkError, Push, Serializer, Set, SimpleError, StructConfig, Verbatim,
+    to_string, to_string_resp3, to_writer, to_writer_resp3,
+};
 
 mod de;
 mod error;
@@ -1,14 +1,297 @@
-use std::{fmt, str};
+use std::{fmt, result, str};
+use std::borrow::Cow;
 use std::io::Write;
 
 use serde::{ser, Serialize};
 
 use crate::{Error, Result};
 
+/// Formats a finite `v` the same way `f64`'s `Display` impl does (no
+/// trailing `.0` on whole numbers, no scientific notation), but via
+/// `ryu`'s allocation-free shortest round-trip algorithm instead of
+/// `to_string`. `ryu` switches to scientific notation for very large or
+/// very small magnitudes where `Display` would still spell out the full
+/// decimal expansion; that's rare enough in practice that falling back to
+/// `to_string` there is worth it to keep the wire format unchanged.
+fn format_f64(buf: &mut ryu::Buffer, v: f64) -> Cow<'_, str> {
+    let formatted = buf.format_finite(v);
+    if formatted.contains(['e', 'E']) {
+        return Cow::Owned(v.to_string());
+    }
+    match formatted.strip_suffix(".0") {
+        Some(stripped) => Cow::Borrowed(stripped),
+        None => Cow::Borrowed(formatted),
+    }
+}
+
+// Magic newtype-struct names the `Serializer` below recognizes to pick a
+// RESP wire type the data model can't otherwise express, the same trick
+// `serde_json`'s `RawValue` uses to smuggle intent through the generic
+// `Serialize` trait. Inspired by ciborium's `Captured`/tag wrappers.
+const SET_NEWTYPE_NAME: &str = "$resp::Set";
+const PUSH_NEWTYPE_NAME: &str = "$resp::Push";
+const SIMPLE_ERROR_NEWTYPE_NAME: &str = "$resp::SimpleError";
+const BULK_ERROR_NEWTYPE_NAME: &str = "$resp::BulkError";
+const VERBATIM_NEWTYPE_NAME: &str = "$resp::Verbatim";
+
+/// Which of the wrapper types below, if any, is currently being unwound
+/// through a `serialize_newtype_struct` call. Consumed by whichever
+/// `serialize_*` method the wrapped value forwards to.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Pending {
+    None,
+    Set,
+    Push,
+    SimpleError,
+    BulkError,
+    Verbatim,
+}
+
+/// Wraps the serializer while it is inside a sequence/tuple/map, tracking
+/// how many elements have gone by so an error from any one element's
+/// `Serialize` impl can be annotated with the index that failed via
+/// [`Error::index`].
+///
+/// Returned as the `SerializeSeq`/`SerializeTuple`/.../`SerializeMap`
+/// associated type, so it has to be as visible as [`Serializer`] itself,
+/// but there's nothing for a caller to actually do with it.
+pub struct IndexedSerializer<'a, W> {
+    ser: &'a mut Serializer<W>,
+    index: usize,
+}
+
+/// Forwards directly to `serialize_bytes`, bypassing the default `[u8]`
+/// sequence impl, the same trick the `serde_bytes` crate uses.
+struct Bytes<'a>(&'a [u8]);
+
+impl<'a> Serialize for Bytes<'a> {
+    fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error>
+        where
+            S: ser::Serializer,
+    {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+/// Wrapper around a sequence that requests RESP3 set (`~<n>\r\n`) encoding.
+///
+/// Under RESP2, where no dedicated set type exists, this falls back to the
+/// usual array encoding.
+pub struct Set<T>(
+    /// The elements of the set.
+    pub Vec<T>,
+);
+
+impl<T: Serialize> Serialize for Set<T> {
+    fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error>
+        where
+            S: ser::Serializer,
+    {
+        serializer.serialize_newtype_struct(SET_NEWTYPE_NAME, &self.0)
+    }
+}
+
+/// Wrapper around a sequence that requests the RESP3 push type
+/// (`><n>\r\n`), used for out-of-band messages.
+///
+/// Under RESP2, where no push type exists, this falls back to the usual
+/// array encoding.
+pub struct Push<T>(
+    /// The elements of the push message.
+    pub Vec<T>,
+);
+
+impl<T: Serialize> Serialize for Push<T> {
+    fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error>
+        where
+            S: ser::Serializer,
+    {
+        serializer.serialize_newtype_struct(PUSH_NEWTYPE_NAME, &self.0)
+    }
+}
+
+/// Forces the RESP simple-error line `-<msg>\r\n`, which no ordinary Rust
+/// value can otherwise reach since every `&str`/`String` serializes to
+/// `+`/`$`. Serializing fails if `msg` contains `\r` or `\n`, since either
+/// would let it break out of its own line and forge extra RESP frames.
+pub struct SimpleError(pub String);
+
+impl Serialize for SimpleError {
+    fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error>
+        where
+            S: ser::Serializer,
+    {
+        serializer.serialize_newtype_struct(SIMPLE_ERROR_NEWTYPE_NAME, &self.0)
+    }
+}
+
+/// Forces the RESP3 bulk-error type `!<len>\r\n<msg>\r\n`, which is
+/// length-prefixed and so can carry any bytes in `msg`. Falls back to a
+/// simple error (`-<msg>\r\n`) under RESP2, which has no bulk-error type —
+/// in that fallback, as with [`SimpleError`], serializing fails if `msg`
+/// contains `\r` or `\n`, since the line-oriented encoding can't represent
+/// them without corrupting the wire.
+pub struct BulkError(pub String);
+
+impl Serialize for BulkError {
+    fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error>
+        where
+            S: ser::Serializer,
+    {
+        serializer.serialize_newtype_struct(BULK_ERROR_NEWTYPE_NAME, &self.0)
+    }
+}
+
+/// Forces the RESP3 verbatim string type `=<len>\r\ntxt:<payload>\r\n`,
+/// where `format` is a 3-byte type hint (e.g. `b"txt"`, `b"mkd"`). Falls
+/// back to a plain bulk string under RESP2, which has no verbatim type.
+pub struct Verbatim {
+    /// The 3-byte format hint prefixed to the payload (e.g. `b"txt"`).
+    pub format: [u8; 3],
+    /// The text payload.
+    pub text: String,
+}
+
+impl Serialize for Verbatim {
+    fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error>
+        where
+            S: ser::Serializer,
+    {
+        let mut payload = Vec::with_capacity(4 + self.text.len());
+        payload.extend_from_slice(&self.format);
+        payload.push(b':');
+        payload.extend_from_slice(self.text.as_bytes());
+        serializer.serialize_newtype_struct(VERBATIM_NEWTYPE_NAME, &Bytes(&payload))
+    }
+}
+
+/// Controls how `serialize_struct`/`serialize_struct_variant` and maps are
+/// rendered on the wire.
+///
+/// Borrows the `StructMapConfig`/`StructTupleConfig` idea from rmp-serde.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StructConfig {
+    /// RESP2: pair-array (`[[k0,v0], [k1,v1], ...]`). RESP3: the native
+    /// map type `%<n>\r\n`. This is the default.
+    #[default]
+    Auto,
+    /// Always `[[k0,v0], [k1,v1], ...]`, regardless of RESP2/RESP3.
+    PairArray,
+    /// Emit only field values as a single `*<n>` array, the natural shape
+    /// for building `SET key value`-style commands.
+    ///
+    /// Only meaningful for `#[derive(Serialize)]` structs, where field
+    /// names are static and recoverable from the type. A genuine map
+    /// (`HashMap`, `BTreeMap`, ...) has no such type-level names to fall
+    /// back on, so `Flat` would silently drop its keys; `serialize_map`
+    /// treats it the same way `Map` does instead, using the native RESP3
+    /// map type when available and `PairArray` under RESP2.
+    Flat,
+    /// Always the RESP3 native map type `%<n>\r\n`. Falls back to
+    /// `PairArray` under RESP2, which has no map type.
+    Map,
+}
+
+/// The struct encoding actually in effect for `serialize_struct`/
+/// `serialize_struct_variant`, once [`StructConfig`] has been resolved
+/// against whether RESP3 is enabled.
+enum EffectiveStructMode {
+    PairArray,
+    Flat,
+    Map,
+}
+
+/// The encoding actually in effect for a genuine `serialize_map` call (a
+/// `HashMap`/`BTreeMap`/etc., as opposed to a `#[derive(Serialize)]`
+/// struct), once [`StructConfig`] has been resolved against whether RESP3
+/// is enabled.
+///
+/// There is no `Flat` variant here: `StructConfig::Flat` drops keys and
+/// keeps only values, which is only safe for a struct, where the field
+/// names are static and recoverable from the type. For a real map the
+/// keys are data, so `Flat` would silently lose them; it degrades the same
+/// way `StructConfig::Map` does instead, picking the native map type under
+/// RESP3 and falling back to `PairArray` only where RESP2 leaves no choice.
+enum EffectiveMapMode {
+    PairArray,
+    Map,
+}
+
 /// Serializer
-pub struct Serializer {
-    /// This string starts empty and RESP is appended as values are serialized.
-    output: Vec<u8>,
+pub struct Serializer<W> {
+    /// RESP is written here as values are serialized.
+    writer: W,
+    /// Whether to emit RESP3 types (maps, booleans, doubles, nulls, ...)
+    /// instead of degrading them to their RESP2 equivalents.
+    resp3: bool,
+    /// One-shot marker set by the wrapper types (`Set`, `Push`,
+    /// `SimpleError`, ...) to redirect the very next write into the RESP
+    /// type they request.
+    pending: Pending,
+    /// How structs and maps are encoded. See [`StructConfig`].
+    struct_config: StructConfig,
+}
+
+impl<W: Write> Serializer<W> {
+    /// Create a serializer that writes RESP2 output into `writer`.
+    pub fn new(writer: W) -> Self {
+        Serializer { writer, resp3: false, pending: Pending::None, struct_config: StructConfig::default() }
+    }
+
+    /// Select how structs and maps are encoded. See [`StructConfig`].
+    pub fn with_struct_config(mut self, config: StructConfig) -> Self {
+        self.struct_config = config;
+        self
+    }
+
+    /// Select whether RESP3 wire types (maps, booleans, doubles, nulls,
+    /// big numbers, ...) are emitted instead of their RESP2 equivalents.
+    pub fn with_resp3(mut self, resp3: bool) -> Self {
+        self.resp3 = resp3;
+        self
+    }
+
+    fn effective_struct_mode(&self) -> EffectiveStructMode {
+        match self.struct_config {
+            StructConfig::Auto if self.resp3 => EffectiveStructMode::Map,
+            StructConfig::Auto => EffectiveStructMode::PairArray,
+            StructConfig::PairArray => EffectiveStructMode::PairArray,
+            StructConfig::Flat => EffectiveStructMode::Flat,
+            StructConfig::Map if self.resp3 => EffectiveStructMode::Map,
+            StructConfig::Map => EffectiveStructMode::PairArray,
+        }
+    }
+
+    fn effective_map_mode(&self) -> EffectiveMapMode {
+        match self.struct_config {
+            StructConfig::Auto if self.resp3 => EffectiveMapMode::Map,
+            StructConfig::Auto => EffectiveMapMode::PairArray,
+            StructConfig::PairArray => EffectiveMapMode::PairArray,
+            StructConfig::Flat if self.resp3 => EffectiveMapMode::Map,
+            StructConfig::Flat => EffectiveMapMode::PairArray,
+            StructConfig::Map if self.resp3 => EffectiveMapMode::Map,
+            StructConfig::Map => EffectiveMapMode::PairArray,
+        }
+    }
+
+    /// Write the `%<n>`/`*<n>` header shared by `serialize_map` and
+    /// `serialize_struct`.
+    fn write_map_header(&mut self, marker: &[u8], len: usize) -> Result<()> {
+        self.writer.write_all(marker)?;
+        self.writer.write_all(len.to_string().as_bytes())?;
+        self.writer.write_all(b"\r\n")?;
+        Ok(())
+    }
+}
+
+/// serialize `value` into `writer`
+pub fn to_writer<W, T>(writer: W, value: &T) -> Result<()>
+    where
+        W: Write,
+        T: Serialize,
+{
+    let mut serializer = Serializer::new(writer);
+    value.serialize(&mut serializer)
 }
 
 /// serialize to string
@@ -16,12 +299,34 @@ pub fn to_string<T>(value: &T) -> Result<String>
     where
         T: Serialize,
 {
-    let mut serializer = Serializer { output: Vec::new() };
-    value.serialize(&mut serializer)?;
-    Ok(String::from_utf8(serializer.output)?)
+    let mut output = Vec::new();
+    to_writer(&mut output, value)?;
+    Ok(String::from_utf8(output)?)
 }
 
-impl<'a> ser::Serializer for &'a mut Serializer {
+/// serialize `value` into `writer` using RESP3 wire types (maps, booleans,
+/// doubles, nulls, big numbers, ...) instead of their RESP2 equivalents
+pub fn to_writer_resp3<W, T>(writer: W, value: &T) -> Result<()>
+    where
+        W: Write,
+        T: Serialize,
+{
+    let mut serializer = Serializer::new(writer).with_resp3(true);
+    value.serialize(&mut serializer)
+}
+
+/// serialize to string using RESP3 wire types (maps, booleans, doubles,
+/// nulls, big numbers, ...) instead of their RESP2 equivalents
+pub fn to_string_resp3<T>(value: &T) -> Result<String>
+    where
+        T: Serialize,
+{
+    let mut output = Vec::new();
+    to_writer_resp3(&mut output, value)?;
+    Ok(String::from_utf8(output)?)
+}
+
+impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
     // The output type produced by this `Serializer` during successful
     // serialization. Most serializers that produce text or binary output should
     // set `Ok = ()` and serialize into an `io::Write` or buffer contained
@@ -35,17 +340,24 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     // compound data structures like sequences and maps. In this case no
     // additional state is required beyond what is already stored in the
     // Serializer struct.
-    type SerializeSeq = Self;
-    type SerializeTuple = Self;
-    type SerializeTupleStruct = Self;
-    type SerializeTupleVariant = Self;
-    type SerializeMap = Self;
+    // The exception is `IndexedSerializer`, which also counts elements so
+    // an error can name the index that failed.
+    type SerializeSeq = IndexedSerializer<'a, W>;
+    type SerializeTuple = IndexedSerializer<'a, W>;
+    type SerializeTupleStruct = IndexedSerializer<'a, W>;
+    type SerializeTupleVariant = IndexedSerializer<'a, W>;
+    type SerializeMap = IndexedSerializer<'a, W>;
     type SerializeStruct = Self;
     type SerializeStructVariant = Self;
 
-    /// 0 for false other for true
+    /// RESP3: `#t\r\n`/`#f\r\n`. RESP2: 0 for false, 1 for true.
     fn serialize_bool(self, v: bool) -> Result<Self::Ok> {
-        self.serialize_i64(v as i64)
+        if self.resp3 {
+            self.writer.write_all(if v { b"#t\r\n" } else { b"#f\r\n" })?;
+            Ok(())
+        } else {
+            self.serialize_i64(v as i64)
+        }
     }
     fn serialize_i8(self, v: i8) -> Result<Self::Ok> {
         self.serialize_i64(v as i64)
@@ -58,9 +370,9 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     }
 
     fn serialize_i64(self, v: i64) -> Result<Self::Ok> {
-        self.output.write_all(b":")?;
-        self.output.write_all(&v.to_string().as_bytes())?;
-        self.output.write_all(b"\r\n")?;
+        self.writer.write_all(b":")?;
+        self.writer.write_all(itoa::Buffer::new().format(v).as_bytes())?;
+        self.writer.write_all(b"\r\n")?;
         Ok(())
     }
 
@@ -75,18 +387,70 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     }
 
     fn serialize_u64(self, v: u64) -> Result<Self::Ok> {
-        self.output.write_all(b":")?;
-        self.output.write_all(&v.to_string().as_bytes())?;
-        self.output.write_all(b"\r\n")?;
+        self.writer.write_all(b":")?;
+        self.writer.write_all(itoa::Buffer::new().format(v).as_bytes())?;
+        self.writer.write_all(b"\r\n")?;
         Ok(())
     }
 
+    // RESP3 has no native 128-bit integer; outside i64/u64 range it uses
+    // the big-number type instead.
+    fn serialize_i128(self, v: i128) -> Result<Self::Ok> {
+        if let Ok(v) = i64::try_from(v) {
+            return self.serialize_i64(v);
+        }
+        let mut buf = itoa::Buffer::new();
+        let formatted = buf.format(v);
+        if self.resp3 {
+            self.writer.write_all(b"(")?;
+            self.writer.write_all(formatted.as_bytes())?;
+            self.writer.write_all(b"\r\n")?;
+            Ok(())
+        } else {
+            self.serialize_str(formatted)
+        }
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Self::Ok> {
+        if let Ok(v) = u64::try_from(v) {
+            return self.serialize_u64(v);
+        }
+        let mut buf = itoa::Buffer::new();
+        let formatted = buf.format(v);
+        if self.resp3 {
+            self.writer.write_all(b"(")?;
+            self.writer.write_all(formatted.as_bytes())?;
+            self.writer.write_all(b"\r\n")?;
+            Ok(())
+        } else {
+            self.serialize_str(formatted)
+        }
+    }
+
     fn serialize_f32(self, v: f32) -> Result<Self::Ok> {
         self.serialize_f64(v as f64)
     }
 
+    /// RESP3: the double type `,<value>\r\n` (`,inf`/`,-inf`/`,nan` for the
+    /// non-finite cases). RESP2: a bulk string, since RESP2 has no numeric
+    /// type wide enough for a float.
     fn serialize_f64(self, v: f64) -> Result<Self::Ok> {
-        self.serialize_str(&v.to_string())
+        if self.resp3 {
+            self.writer.write_all(b",")?;
+            if v.is_nan() {
+                self.writer.write_all(b"nan")?;
+            } else if v.is_infinite() {
+                self.writer.write_all(if v.is_sign_negative() { b"-inf" } else { b"inf" })?;
+            } else {
+                self.writer.write_all(format_f64(&mut ryu::Buffer::new(), v).as_bytes())?;
+            }
+            self.writer.write_all(b"\r\n")?;
+            Ok(())
+        } else if v.is_finite() {
+            self.serialize_str(&format_f64(&mut ryu::Buffer::new(), v))
+        } else {
+            self.serialize_str(&v.to_string())
+        }
     }
 
     fn serialize_char(self, v: char) -> Result<Self::Ok> {
@@ -94,29 +458,82 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     }
 
     fn serialize_str(self, v: &str) -> Result<Self::Ok> {
+        let pending = self.pending;
+        self.pending = Pending::None;
+        match pending {
+            Pending::SimpleError => {
+                if v.as_bytes().contains(&b'\r') || v.as_bytes().contains(&b'\n') {
+                    return Err(Error::crlf_in_error_message());
+                }
+                self.writer.write_all(b"-")?;
+                self.writer.write_all(v.as_bytes())?;
+                self.writer.write_all(b"\r\n")?;
+                return Ok(());
+            }
+            Pending::BulkError if self.resp3 => {
+                self.writer.write_all(b"!")?;
+                self.writer.write_all(v.len().to_string().as_bytes())?;
+                self.writer.write_all(b"\r\n")?;
+                self.writer.write_all(v.as_bytes())?;
+                self.writer.write_all(b"\r\n")?;
+                return Ok(());
+            }
+            // under RESP2, BulkError has no dedicated type, so it falls
+            // back to a simple error
+            Pending::BulkError => {
+                if v.as_bytes().contains(&b'\r') || v.as_bytes().contains(&b'\n') {
+                    return Err(Error::crlf_in_error_message());
+                }
+                self.writer.write_all(b"-")?;
+                self.writer.write_all(v.as_bytes())?;
+                self.writer.write_all(b"\r\n")?;
+                return Ok(());
+            }
+            _ => {}
+        }
+
         if v.as_bytes().contains(&b'\r') || v.as_bytes().contains(&b'\n') {
             self.serialize_bytes(v.as_bytes())?;
         } else {
-            self.output.write_all(b"+")?;
-            self.output.write_all(&v.as_bytes())?;
-            self.output.write_all(b"\r\n")?;
+            self.writer.write_all(b"+")?;
+            self.writer.write_all(&v.as_bytes())?;
+            self.writer.write_all(b"\r\n")?;
         }
         Ok(())
     }
 
     fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok> {
-        self.output.write_all(b"$")?;
-        self.output.write_all(&v.len().to_string().as_bytes())?;
-        self.output.write_all(b"\r\n")?;
-        self.output.write_all(v)?;
-        self.output.write_all(b"\r\n")?;
+        let pending = self.pending;
+        self.pending = Pending::None;
+        if let Pending::Verbatim = pending {
+            if self.resp3 {
+                self.writer.write_all(b"=")?;
+                self.writer.write_all(v.len().to_string().as_bytes())?;
+                self.writer.write_all(b"\r\n")?;
+                self.writer.write_all(v)?;
+                self.writer.write_all(b"\r\n")?;
+                return Ok(());
+            }
+        }
+
+        self.writer.write_all(b"$")?;
+        self.writer.write_all(&v.len().to_string().as_bytes())?;
+        self.writer.write_all(b"\r\n")?;
+        self.writer.write_all(v)?;
+        self.writer.write_all(b"\r\n")?;
         Ok(())
     }
 
-    // use an empty array to represent None
+    // RESP3: the null type `_\r\n`. RESP2: an empty array, as RESP2 has no
+    // dedicated null.
     // None => []
     fn serialize_none(self) -> Result<Self::Ok> {
-        Vec::<String>::new().serialize(self)
+        if self.resp3 {
+            self.writer.write_all(b"_\r\n")?;
+            Ok(())
+        } else {
+            Vec::<String>::new().serialize(self)
+        }
     }
 
     // use an array with a single object as Some
@@ -125,12 +542,20 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         where
             T: Serialize,
     {
-        vec![value].serialize(self)
+        if self.resp3 {
+            value.serialize(self)
+        } else {
+            vec![value].serialize(self)
+        }
     }
 
-    // use null as unit which is "$-1\r\n"
+    // RESP3: the null type `_\r\n`. RESP2: null bulk string `$-1\r\n`.
     fn serialize_unit(self) -> Result<Self::Ok> {
-        self.output.write_all(b"$-1\r\n")?;
+        if self.resp3 {
+            self.writer.write_all(b"_\r\n")?;
+        } else {
+            self.writer.write_all(b"$-1\r\n")?;
+        }
         Ok(())
     }
 
@@ -153,10 +578,18 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     // struct Millimeters(u8)
     // serializers are encouraged to treat newtype structs as
     // insignificant wrappers around the data they contain
-    fn serialize_newtype_struct<T: ?Sized>(self, _name: &'static str, value: &T) -> Result<Self::Ok>
+    fn serialize_newtype_struct<T: ?Sized>(self, name: &'static str, value: &T) -> Result<Self::Ok>
         where
             T: Serialize,
     {
+        self.pending = match name {
+            SET_NEWTYPE_NAME => Pending::Set,
+            PUSH_NEWTYPE_NAME => Pending::Push,
+            SIMPLE_ERROR_NEWTYPE_NAME => Pending::SimpleError,
+            BULK_ERROR_NEWTYPE_NAME => Pending::BulkError,
+            VERBATIM_NEWTYPE_NAME => Pending::Verbatim,
+            _ => Pending::None,
+        };
         value.serialize(self)
     }
 
@@ -172,7 +605,7 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         where
             T: Serialize,
     {
-        self.output.write_all(b"*2\r\n")?;
+        self.writer.write_all(b"*2\r\n")?;
         variant.serialize(&mut *self)?;
         value.serialize(&mut *self)?;
         Ok(())
@@ -181,11 +614,18 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     // The start of the sequence, each value, and the end are three separate
     // method calls. This one is responsible only for serializing the start
     fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
-        let len = len.ok_or(Error::LenNotKnown)?;
-        self.output.write_all(b"*")?;
-        self.output.write_all(&len.to_string().as_bytes())?;
-        self.output.write_all(b"\r\n")?;
-        Ok(self)
+        let len = len.ok_or_else(Error::len_not_known)?;
+        let pending = self.pending;
+        self.pending = Pending::None;
+        let marker: &[u8] = match pending {
+            Pending::Set if self.resp3 => b"~",
+            Pending::Push if self.resp3 => b">",
+            _ => b"*",
+        };
+        self.writer.write_all(marker)?;
+        self.writer.write_all(&len.to_string().as_bytes())?;
+        self.writer.write_all(b"\r\n")?;
+        Ok(IndexedSerializer { ser: self, index: 0 })
     }
 
     // [value0, value1, ...]
@@ -210,22 +650,31 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         variant: &'static str,
         len: usize,
     ) -> Result<Self::SerializeTupleVariant> {
-        self.output.write_all(b"*2\r\n")?;
+        self.writer.write_all(b"*2\r\n")?;
         variant.serialize(&mut *self)?;
         self.serialize_seq(Some(len))
     }
 
-    //  [[k0,v0], [k1,v1], ...]
+    // Encoding is picked by `StructConfig`: pair-array, or (RESP3 only)
+    // the native map type `%<n>\r\n`. `Flat` only applies to structs (see
+    // `EffectiveMapMode`), so it degrades the same way `Map` does here.
     fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap> {
-        let len = len.ok_or(Error::LenNotKnown)?;
-        self.output.write_all(b"*")?;
-        self.output.write_all(len.to_string().as_bytes())?;
-        self.output.write_all(b"\r\n")?;
-        Ok(self)
+        let len = len.ok_or_else(Error::len_not_known)?;
+        let marker: &[u8] = match self.effective_map_mode() {
+            EffectiveMapMode::Map => b"%",
+            EffectiveMapMode::PairArray => b"*",
+        };
+        self.write_map_header(marker, len)?;
+        Ok(IndexedSerializer { ser: self, index: 0 })
     }
 
     fn serialize_struct(self, _name: &'static str, len: usize) -> Result<Self::SerializeStruct> {
-        self.serialize_map(Some(len))
+        let marker: &[u8] = match self.effective_struct_mode() {
+            EffectiveStructMode::Map => b"%",
+            EffectiveStructMode::PairArray | EffectiveStructMode::Flat => b"*",
+        };
+        self.write_map_header(marker, len)?;
+        Ok(self)
     }
 
     // [variant, [[k0,v0], [k1,v1], ...]]
@@ -236,9 +685,14 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         variant: &'static str,
         len: usize,
     ) -> Result<Self::SerializeStructVariant> {
-        self.output.write_all(b"*2\r\n")?;
+        self.writer.write_all(b"*2\r\n")?;
         variant.serialize(&mut *self)?;
-        self.serialize_seq(Some(len))
+        let marker: &[u8] = match self.effective_struct_mode() {
+            EffectiveStructMode::Map => b"%",
+            EffectiveStructMode::PairArray | EffectiveStructMode::Flat => b"*",
+        };
+        self.write_map_header(marker, len)?;
+        Ok(self)
     }
 
     fn collect_str<T: ?Sized>(self, value: &T) -> Result<Self::Ok>
@@ -253,7 +707,7 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     }
 }
 
-impl<'a> ser::SerializeSeq for &'a mut Serializer {
+impl<'a, W: Write> ser::SerializeSeq for IndexedSerializer<'a, W> {
     type Ok = ();
     type Error = Error;
 
@@ -261,7 +715,9 @@ impl<'a> ser::SerializeSeq for &'a mut Serializer {
         where
             T: Serialize,
     {
-        value.serialize(&mut **self)
+        let index = self.index;
+        self.index += 1;
+        value.serialize(&mut *self.ser).map_err(|e| e.index(index))
     }
 
     fn end(self) -> Result<Self::Ok> {
@@ -269,7 +725,7 @@ impl<'a> ser::SerializeSeq for &'a mut Serializer {
     }
 }
 
-impl<'a> ser::SerializeTuple for &'a mut Serializer {
+impl<'a, W: Write> ser::SerializeTuple for IndexedSerializer<'a, W> {
     type Ok = ();
     type Error = Error;
 
@@ -277,7 +733,9 @@ impl<'a> ser::SerializeTuple for &'a mut Serializer {
         where
             T: Serialize,
     {
-        value.serialize(&mut **self)
+        let index = self.index;
+        self.index += 1;
+        value.serialize(&mut *self.ser).map_err(|e| e.index(index))
     }
 
     fn end(self) -> Result<Self::Ok> {
@@ -285,7 +743,7 @@ impl<'a> ser::SerializeTuple for &'a mut Serializer {
     }
 }
 
-impl<'a> ser::SerializeTupleStruct for &'a mut Serializer {
+impl<'a, W: Write> ser::SerializeTupleStruct for IndexedSerializer<'a, W> {
     type Ok = ();
     type Error = Error;
 
@@ -293,7 +751,9 @@ impl<'a> ser::SerializeTupleStruct for &'a mut Serializer {
         where
             T: Serialize,
     {
-        value.serialize(&mut **self)
+        let index = self.index;
+        self.index += 1;
+        value.serialize(&mut *self.ser).map_err(|e| e.index(index))
     }
 
     fn end(self) -> Result<Self::Ok> {
@@ -301,7 +761,7 @@ impl<'a> ser::SerializeTupleStruct for &'a mut Serializer {
     }
 }
 
-impl<'a> ser::SerializeTupleVariant for &'a mut Serializer {
+impl<'a, W: Write> ser::SerializeTupleVariant for IndexedSerializer<'a, W> {
     type Ok = ();
     type Error = Error;
 
@@ -309,7 +769,9 @@ impl<'a> ser::SerializeTupleVariant for &'a mut Serializer {
         where
             T: Serialize,
     {
-        value.serialize(&mut **self)
+        let index = self.index;
+        self.index += 1;
+        value.serialize(&mut *self.ser).map_err(|e| e.index(index))
     }
 
     fn end(self) -> Result<Self::Ok> {
@@ -317,22 +779,28 @@ impl<'a> ser::SerializeTupleVariant for &'a mut Serializer {
     }
 }
 
-impl<'a> ser::SerializeMap for &'a mut Serializer {
+impl<'a, W: Write> ser::SerializeMap for IndexedSerializer<'a, W> {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_key<T: ?Sized>(&mut self, _key: &T) -> Result<()>
+    fn serialize_key<T: ?Sized>(&mut self, key: &T) -> Result<()>
         where
             T: Serialize,
     {
-        Ok(())
+        let index = self.index;
+        if let EffectiveMapMode::PairArray = self.ser.effective_map_mode() {
+            self.ser.writer.write_all(b"*2\r\n")?;
+        }
+        key.serialize(&mut *self.ser).map_err(|e| e.index(index))
     }
 
-    fn serialize_value<T: ?Sized>(&mut self, _value: &T) -> Result<()>
+    fn serialize_value<T: ?Sized>(&mut self, value: &T) -> Result<()>
         where
             T: Serialize,
     {
-        Ok(())
+        let index = self.index;
+        self.index += 1;
+        value.serialize(&mut *self.ser).map_err(|e| e.index(index))
     }
 
     fn serialize_entry<K: ?Sized, V: ?Sized>(&mut self, key: &K, value: &V) -> Result<()>
@@ -340,9 +808,19 @@ impl<'a> ser::SerializeMap for &'a mut Serializer {
             K: Serialize,
             V: Serialize,
     {
-        self.output.write_all(b"*2\r\n")?;
-        key.serialize(&mut **self)?;
-        value.serialize(&mut **self)?;
+        let index = self.index;
+        self.index += 1;
+        match self.ser.effective_map_mode() {
+            EffectiveMapMode::PairArray => {
+                self.ser.writer.write_all(b"*2\r\n")?;
+                key.serialize(&mut *self.ser).map_err(|e| e.index(index))?;
+                value.serialize(&mut *self.ser).map_err(|e| e.index(index))?;
+            }
+            EffectiveMapMode::Map => {
+                key.serialize(&mut *self.ser).map_err(|e| e.index(index))?;
+                value.serialize(&mut *self.ser).map_err(|e| e.index(index))?;
+            }
+        }
 
         Ok(())
     }
@@ -352,7 +830,7 @@ impl<'a> ser::SerializeMap for &'a mut Serializer {
     }
 }
 
-impl<'a> ser::SerializeStruct for &'a mut Serializer {
+impl<W: Write> ser::SerializeStruct for &mut Serializer<W> {
     type Ok = ();
     type Error = Error;
 
@@ -360,9 +838,20 @@ impl<'a> ser::SerializeStruct for &'a mut Serializer {
         where
             T: Serialize,
     {
-        self.output.write_all(b"*2\r\n")?;
-        key.serialize(&mut **self)?;
-        value.serialize(&mut **self)?;
+        match self.effective_struct_mode() {
+            EffectiveStructMode::PairArray => {
+                self.writer.write_all(b"*2\r\n")?;
+                key.serialize(&mut **self)?;
+                value.serialize(&mut **self).map_err(|e| e.field(key))?;
+            }
+            EffectiveStructMode::Map => {
+                key.serialize(&mut **self)?;
+                value.serialize(&mut **self).map_err(|e| e.field(key))?;
+            }
+            EffectiveStructMode::Flat => {
+                value.serialize(&mut **self).map_err(|e| e.field(key))?;
+            }
+        }
         Ok(())
     }
 
@@ -371,7 +860,7 @@ impl<'a> ser::SerializeStruct for &'a mut Serializer {
     }
 }
 
-impl<'a> ser::SerializeStructVariant for &'a mut Serializer {
+impl<W: Write> ser::SerializeStructVariant for &mut Serializer<W> {
     type Ok = ();
     type Error = Error;
 
@@ -379,9 +868,20 @@ impl<'a> ser::SerializeStructVariant for &'a mut Serializer {
         where
             T: Serialize,
     {
-        self.output.write_all(b"*2\r\n")?;
-        key.serialize(&mut **self)?;
-        value.serialize(&mut **self)?;
+        match self.effective_struct_mode() {
+            EffectiveStructMode::PairArray => {
+                self.writer.write_all(b"*2\r\n")?;
+                key.serialize(&mut **self)?;
+                value.serialize(&mut **self).map_err(|e| e.field(key))?;
+            }
+            EffectiveStructMode::Map => {
+                key.serialize(&mut **self)?;
+                value.serialize(&mut **self).map_err(|e| e.field(key))?;
+            }
+            EffectiveStructMode::Flat => {
+                value.serialize(&mut **self).map_err(|e| e.field(key))?;
+            }
+        }
         Ok(())
     }
 
@@ -456,4 +956,256 @@ mod tests {
         let expected = "*2\r\n+Struct\r\n*1\r\n*2\r\n+a\r\n:1\r\n";
         assert_eq!(to_string(&s).unwrap(), expected);
     }
+
+    #[test]
+    fn test_resp3_scalars() {
+        assert_eq!(to_string_resp3(&true).unwrap(), "#t\r\n");
+        assert_eq!(to_string_resp3(&false).unwrap(), "#f\r\n");
+        assert_eq!(to_string_resp3(&1.5f64).unwrap(), ",1.5\r\n");
+        assert_eq!(to_string_resp3(&f64::INFINITY).unwrap(), ",inf\r\n");
+        assert_eq!(to_string_resp3(&f64::NEG_INFINITY).unwrap(), ",-inf\r\n");
+        assert_eq!(to_string_resp3(&f64::NAN).unwrap(), ",nan\r\n");
+        assert_eq!(to_string_resp3(&None::<u32>).unwrap(), "_\r\n");
+        assert_eq!(to_string_resp3(&Some(1u32)).unwrap(), ":1\r\n");
+        assert_eq!(to_string_resp3(&(170141183460469231731687303715884105727i128)).unwrap(),
+                   "(170141183460469231731687303715884105727\r\n");
+        // i128/u128 values that fit in i64/u64 are plain integers, not big numbers.
+        assert_eq!(to_string_resp3(&5i128).unwrap(), ":5\r\n");
+        assert_eq!(to_string_resp3(&5u128).unwrap(), ":5\r\n");
+    }
+
+    // `ryu` renders whole-number floats with a trailing `.0` (unlike
+    // `f64::to_string`), so whole numbers need their own coverage to catch
+    // a regression there.
+    #[test]
+    fn test_whole_number_float() {
+        assert_eq!(to_string_resp3(&1.0f64).unwrap(), ",1\r\n");
+        assert_eq!(to_string_resp3(&(-2.0f64)).unwrap(), ",-2\r\n");
+        assert_eq!(to_string(&100.0f64).unwrap(), "+100\r\n");
+    }
+
+    // `ryu::Buffer::format_finite` switches to scientific notation
+    // (`"1e20"`) for very large magnitudes, where `format_f64` falls back
+    // to `to_string` to keep the wire format's plain-decimal shape.
+    #[test]
+    fn test_large_float_avoids_scientific_notation() {
+        assert_eq!(to_string_resp3(&1e20f64).unwrap(), ",100000000000000000000\r\n");
+    }
+
+    #[test]
+    fn test_resp3_struct() {
+        #[derive(Serialize)]
+        struct Test {
+            int: u32,
+            seq: Vec<&'static str>,
+        }
+
+        let test = Test {
+            int: 1,
+            seq: vec!["a", "b"],
+        };
+        // %2\r\n
+        //   +int\r\n
+        //   :1\r\n
+        //   +seq\r\n
+        //   *2\r\n
+        //     +a\r\n
+        //     +b\r\n
+        let expected = "%2\r\n+int\r\n:1\r\n+seq\r\n*2\r\n+a\r\n+b\r\n";
+        assert_eq!(to_string_resp3(&test).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_resp3_set() {
+        let set = Set(vec![1, 2, 3]);
+        let expected = "~3\r\n:1\r\n:2\r\n:3\r\n";
+        assert_eq!(to_string_resp3(&set).unwrap(), expected);
+
+        // under RESP2 there's no set type, so it degrades to an array
+        let set = Set(vec![1, 2, 3]);
+        let expected = "*3\r\n:1\r\n:2\r\n:3\r\n";
+        assert_eq!(to_string(&set).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_struct_config_flat() {
+        #[derive(Serialize)]
+        struct Test {
+            int: u32,
+            seq: Vec<&'static str>,
+        }
+
+        let test = Test {
+            int: 1,
+            seq: vec!["a", "b"],
+        };
+
+        let mut buf = Vec::new();
+        let mut serializer = Serializer::new(&mut buf).with_struct_config(StructConfig::Flat);
+        test.serialize(&mut serializer).unwrap();
+        // *2\r\n
+        //   :1\r\n
+        //   *2\r\n
+        //     +a\r\n
+        //     +b\r\n
+        assert_eq!(buf, b"*2\r\n:1\r\n*2\r\n+a\r\n+b\r\n");
+    }
+
+    // `StructConfig::Flat` only makes sense for structs, whose field names
+    // are static and recoverable from the type. A real map's keys are
+    // data, so `Flat` must not drop them; `serialize_map` falls back to
+    // `Map`/`PairArray` the same way `StructConfig::Map` does instead.
+    #[test]
+    fn test_struct_config_flat_does_not_drop_map_keys() {
+        use std::collections::BTreeMap;
+
+        let mut map = BTreeMap::new();
+        map.insert("key", "foo");
+
+        let mut buf = Vec::new();
+        let mut serializer = Serializer::new(&mut buf).with_struct_config(StructConfig::Flat);
+        map.serialize(&mut serializer).unwrap();
+        assert_eq!(buf, b"*1\r\n*2\r\n+key\r\n+foo\r\n");
+    }
+
+    #[test]
+    fn test_struct_config_flat_uses_native_map_under_resp3() {
+        use std::collections::BTreeMap;
+
+        let mut map = BTreeMap::new();
+        map.insert("key", "foo");
+
+        let mut buf = Vec::new();
+        let mut serializer = Serializer::new(&mut buf)
+            .with_struct_config(StructConfig::Flat)
+            .with_resp3(true);
+        map.serialize(&mut serializer).unwrap();
+        assert_eq!(buf, b"%1\r\n+key\r\n+foo\r\n");
+    }
+
+    #[test]
+    fn test_struct_config_map_falls_back_under_resp2() {
+        #[derive(Serialize)]
+        struct Test {
+            int: u32,
+        }
+
+        let mut buf = Vec::new();
+        let mut serializer = Serializer::new(&mut buf).with_struct_config(StructConfig::Map);
+        Test { int: 1 }.serialize(&mut serializer).unwrap();
+        assert_eq!(buf, b"*1\r\n*2\r\n+int\r\n:1\r\n");
+    }
+
+    #[test]
+    fn test_to_writer() {
+        #[derive(Serialize)]
+        struct Test {
+            int: u32,
+        }
+
+        let mut buf = Vec::new();
+        to_writer(&mut buf, &Test { int: 1 }).unwrap();
+        assert_eq!(buf, b"*1\r\n*2\r\n+int\r\n:1\r\n");
+    }
+
+    #[test]
+    fn test_with_resp3() {
+        let mut buf = Vec::new();
+        let mut serializer = Serializer::new(&mut buf).with_resp3(true);
+        true.serialize(&mut serializer).unwrap();
+        assert_eq!(buf, b"#t\r\n");
+    }
+
+    #[test]
+    fn test_simple_error() {
+        let err = SimpleError("ERR unknown command".to_string());
+        assert_eq!(to_string(&err).unwrap(), "-ERR unknown command\r\n");
+        assert_eq!(to_string_resp3(&err).unwrap(), "-ERR unknown command\r\n");
+    }
+
+    #[test]
+    fn test_bulk_error() {
+        let err = BulkError("ERR unknown command".to_string());
+        assert_eq!(to_string_resp3(&err).unwrap(), "!19\r\nERR unknown command\r\n");
+        // RESP2 has no bulk-error type, so it falls back to a simple error
+        assert_eq!(to_string(&err).unwrap(), "-ERR unknown command\r\n");
+    }
+
+    #[test]
+    fn test_simple_error_rejects_crlf() {
+        let err = SimpleError("ERR bad arg\r\n$6\r\nINJECT\r\n".to_string());
+        assert!(to_string(&err).is_err());
+        assert!(to_string_resp3(&err).is_err());
+    }
+
+    #[test]
+    fn test_bulk_error_rejects_crlf_under_resp2() {
+        let err = BulkError("ERR bad arg\r\n$6\r\nINJECT\r\n".to_string());
+        // under RESP3 the bulk-error type is length-prefixed, so it can
+        // carry any bytes, including `\r`/`\n`
+        assert!(to_string_resp3(&err).is_ok());
+        // under RESP2 it falls back to the line-oriented simple error,
+        // which can't represent `\r`/`\n` without corrupting the wire
+        assert!(to_string(&err).is_err());
+    }
+
+    #[test]
+    fn test_verbatim() {
+        let v = Verbatim { format: *b"txt", text: "Some string".to_string() };
+        assert_eq!(to_string_resp3(&v).unwrap(), "=15\r\ntxt:Some string\r\n");
+        // RESP2 has no verbatim type, so it falls back to a plain bulk string
+        assert_eq!(to_string(&v).unwrap(), "$15\r\ntxt:Some string\r\n");
+    }
+
+    #[test]
+    fn test_push() {
+        let push = Push(vec![1, 2, 3]);
+        assert_eq!(to_string_resp3(&push).unwrap(), ">3\r\n:1\r\n:2\r\n:3\r\n");
+        // RESP2 has no push type, so it falls back to a plain array
+        assert_eq!(to_string(&push).unwrap(), "*3\r\n:1\r\n:2\r\n:3\r\n");
+    }
+
+    #[test]
+    fn test_error_path_context() {
+        struct Boom;
+
+        impl Serialize for Boom {
+            fn serialize<S>(&self, _serializer: S) -> result::Result<S::Ok, S::Error>
+                where
+                    S: ser::Serializer,
+            {
+                Err(<S::Error as ser::Error>::custom("boom"))
+            }
+        }
+
+        #[derive(Serialize)]
+        struct Test {
+            int: u32,
+            seq: Vec<Boom>,
+        }
+
+        let test = Test { int: 1, seq: vec![Boom] };
+        let err = to_string(&test).unwrap_err();
+        assert_eq!(err.to_string(), "field `seq` -> index 0 -> boom");
+    }
+
+    #[test]
+    fn test_error_preserves_io_source() {
+        use std::{error, io};
+
+        struct FailingWriter;
+
+        impl Write for FailingWriter {
+            fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+                Err(io::Error::other("disk full"))
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let err = to_writer(FailingWriter, &1u32).unwrap_err();
+        assert_eq!(err.to_string(), "disk full");
+        assert!(error::Error::source(&err).is_some());
+    }
 }